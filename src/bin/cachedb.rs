@@ -0,0 +1,272 @@
+// -----------------------------------------------------------------------------
+// Compact cache database backend for NICOS.
+//
+// This program is free software; you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation; either version 2 of the License, or (at your option) any later
+// version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program; if not, write to the Free Software Foundation, Inc.,
+// 59 Temple Place, Suite 330, Boston, MA  02111-1307  USA
+//
+// Module authors:
+//   Georg Brandl <g.brandl@fz-juelich.de>
+//
+// -----------------------------------------------------------------------------
+//
+//! Operator CLI: convert a flatfile database to the compact format, dump
+//! decoded entries back out of one, or verify one's internal consistency.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::io::{BufRead, BufReader};
+use anyhow::{Context, Result, bail};
+use clap::{Parser, Subcommand};
+use fs_err::PathExt;
+
+use nicos_compact_cachedb::dicts::Dicts;
+use nicos_compact_cachedb::dayfile::{DayFile, RawValue};
+use nicos_compact_cachedb::history::{HistoryStore, parse_day_filename};
+
+#[derive(Parser)]
+#[command(name = "cachedb", about = "Convert, inspect and verify compact NICOS cache databases")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Convert a flatfile cache database to the compact format.
+    Convert {
+        indir: PathBuf,
+        outdir: PathBuf,
+    },
+    /// Print decoded entries from a compact database.
+    Dump {
+        outdir: PathBuf,
+        /// Limit to one key, given as "category/subkey".
+        #[arg(long)]
+        key: Option<String>,
+        /// Limit to entries at or after this timestamp.
+        #[arg(long)]
+        from: Option<f64>,
+        /// Limit to entries at or before this timestamp.
+        #[arg(long)]
+        to: Option<f64>,
+    },
+    /// Check that every day file is internally consistent with the dictionaries.
+    Verify {
+        outdir: PathBuf,
+    },
+}
+
+fn main() {
+    if let Err(e) = main_inner() {
+        eprintln!("Error: {:#}", e);
+        std::process::exit(1);
+    }
+}
+
+fn main_inner() -> Result<()> {
+    match Cli::parse().command {
+        Command::Convert { indir, outdir } => do_convert(&indir, &outdir),
+        Command::Dump { outdir, key, from, to } => do_dump(&outdir, key, from, to),
+        Command::Verify { outdir } => do_verify(&outdir),
+    }
+}
+
+fn do_convert(indir: &Path, outdir: &Path) -> Result<()> {
+    if outdir.exists() {
+        if outdir.fs_err_read_dir()?.next().is_some() {
+            bail!("outdir must be empty if it exists");
+        }
+    } else {
+        fs_err::create_dir_all(outdir)?;
+    }
+
+    let dicts = Arc::new(Dicts::default());
+
+    for subdir in indir.fs_err_read_dir()?.flatten() {
+        if let Some(year) = subdir.file_name().to_str().and_then(|s| s.parse::<u32>().ok()) {
+            if (2010..2100).contains(&year) {
+                process_year(year, &subdir.path(), outdir, &dicts)
+                    .with_context(|| format!("Processing {}", subdir.path().display()))?;
+            }
+        }
+    }
+
+    dicts.save(outdir)?;
+
+    Ok(())
+}
+
+/// One day's worth of conversion work: the input directory of per-category
+/// flatfiles, and the compact day file it should produce.
+struct DayTask {
+    indir: PathBuf,
+    outfile: PathBuf,
+}
+
+/// Convert all days of `year`, fanning the work for each day out across a
+/// pool of worker threads. Only the shared `dicts` interner needs
+/// synchronization; each day still produces one independent [`DayFile`].
+fn process_year(year: u32, ydir: &Path, outdir: &Path, dicts: &Arc<Dicts>) -> Result<()> {
+    let mut tasks = Vec::new();
+    for subdir in ydir.fs_err_read_dir()? {
+        let subdir = subdir?;
+        if let Some(split) = subdir.file_name().to_str().map(|s| s.split('-')) {
+            let mut split = split.filter_map(|s| s.parse::<u32>().ok());
+            if let (Some(month), Some(day)) = (split.next(), split.next()) {
+                let filename = format!("{:04}-{:02}-{:02}", year, month, day);
+                tasks.push(DayTask { indir: subdir.path(), outfile: outdir.join(filename) });
+            }
+        }
+    }
+
+    let tasks = Mutex::new(tasks);
+    let errors = Mutex::new(Vec::new());
+    let nworkers = std::thread::available_parallelism().map_or(1, |n| n.get());
+
+    std::thread::scope(|scope| {
+        for _ in 0..nworkers {
+            scope.spawn(|| loop {
+                let task = tasks.lock().unwrap().pop();
+                let Some(task) = task else { break };
+                println!("Processing {}...", task.outfile.display());
+                if let Err(e) = process_day(&task.indir, &task.outfile, dicts)
+                    .with_context(|| format!("Processing {}", task.indir.display()))
+                {
+                    errors.lock().unwrap().push(e);
+                }
+            });
+        }
+    });
+
+    match errors.into_inner().unwrap().into_iter().next() {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+fn process_day(ddir: &Path, outfile: &Path, dicts: &Dicts) -> Result<()> {
+    let mut dayfile = DayFile::create(outfile)?;
+    let mut line = String::new();
+
+    for filename in ddir.fs_err_read_dir()? {
+        let filename = filename?;
+        if let Some(cat) = filename.file_name().to_str() {
+            let catindex = dicts.key_index(cat.as_bytes());
+            let mut file = BufReader::new(fs_err::File::open(filename.path())?);
+            while let Ok(n) = file.read_line(&mut line) {
+                if n == 0 {
+                    break;
+                }
+                let mut parts = line.trim().splitn(4, '\t');
+                if let (Some(subkey), Some(tstamp), Some(op), Some(value)) =
+                    (parts.next(), parts.next(), parts.next(), parts.next())
+                {
+                    let subkeyindex = dicts.key_index(subkey.as_bytes());
+                    let timestamp = tstamp.parse().expect("valid timestamp");
+                    let expiring = op == "-";
+                    dayfile.add_entry(catindex, subkeyindex, value.as_bytes(),
+                                      timestamp, expiring, dicts).expect("adding succeeds");
+                }
+                line.clear();
+            }
+        }
+    }
+    Ok(())
+}
+
+fn do_dump(outdir: &Path, key: Option<String>, from: Option<f64>, to: Option<f64>) -> Result<()> {
+    let from = from.unwrap_or(f64::MIN);
+    let to = to.unwrap_or(f64::MAX);
+
+    if let Some(key) = key {
+        let (cat, subkey) = key.split_once('/')
+            .context("--key must be in the form category/subkey")?;
+        let store = HistoryStore::open(outdir)?;
+        for entry in store.query(cat.as_bytes(), subkey.as_bytes(), from, to)? {
+            println!("{}\t{}\t{}\t{}", entry.timestamp, key,
+                      if entry.expiring { "-" } else { "+" },
+                      String::from_utf8_lossy(&entry.value));
+        }
+        return Ok(());
+    }
+
+    let dicts = Dicts::load(outdir)?;
+    let mut day_files: Vec<_> = outdir.fs_err_read_dir()?
+        .filter_map(Result::ok)
+        .filter(|e| e.file_name().to_str().is_some_and(|n| parse_day_filename(n).is_some()))
+        .collect();
+    day_files.sort_by_key(|e| e.file_name());
+
+    for day_file in day_files {
+        for entry in DayFile::open(&day_file.path())?.resolved(&dicts) {
+            let entry = entry?;
+            if entry.timestamp < from || entry.timestamp > to {
+                continue;
+            }
+            let cat = dicts.key(entry.catindex).context("cat index out of bounds")?;
+            let subkey = dicts.key(entry.subkeyindex).context("subkey index out of bounds")?;
+            println!("{}\t{}/{}\t{}\t{}", entry.timestamp,
+                      String::from_utf8_lossy(&cat),
+                      String::from_utf8_lossy(&subkey),
+                      if entry.expiring { "-" } else { "+" },
+                      String::from_utf8_lossy(&entry.value));
+        }
+    }
+    Ok(())
+}
+
+fn do_verify(outdir: &Path) -> Result<()> {
+    let dicts = Dicts::load(outdir)?;
+    let num_keys = dicts.key_count();
+    let num_values = dicts.value_count();
+    let mut nerrors = 0u64;
+
+    let mut day_files: Vec<_> = outdir.fs_err_read_dir()?
+        .filter_map(Result::ok)
+        .filter(|e| e.file_name().to_str().is_some_and(|n| parse_day_filename(n).is_some()))
+        .collect();
+    day_files.sort_by_key(|e| e.file_name());
+
+    for day_file in day_files {
+        let name = day_file.file_name();
+        let name = name.to_string_lossy();
+        for raw in DayFile::open(&day_file.path())? {
+            match raw {
+                Ok(raw) => {
+                    if raw.catindex as u32 >= num_keys || raw.subkeyindex as u32 >= num_keys {
+                        eprintln!("{name}: key index out of bounds (cat={}, subkey={})",
+                                  raw.catindex, raw.subkeyindex);
+                        nerrors += 1;
+                    }
+                    if let RawValue::Indexed(idx) = raw.value {
+                        if idx >= num_values {
+                            eprintln!("{name}: value index {idx} out of bounds");
+                            nerrors += 1;
+                        }
+                    }
+                }
+                Err(e) => {
+                    eprintln!("{name}: {e}");
+                    nerrors += 1;
+                }
+            }
+        }
+    }
+
+    if nerrors > 0 {
+        bail!("{nerrors} problem(s) found");
+    }
+    println!("OK");
+    Ok(())
+}
@@ -22,7 +22,7 @@
 //
 //! Loading and storing of entries for one day.
 
-use std::io::{self, Write, BufWriter};
+use std::io::{self, Read, Seek, SeekFrom, Write, BufReader, BufWriter};
 use std::path::Path;
 use byteorder::{LE, ByteOrder};
 use fs_err::File;
@@ -31,6 +31,7 @@ use crate::dicts::Dicts;
 const FLAG_EXPIRING: u32 = 1 << 31;
 const FLAG_ENCODED: u32 = 1 << 30;
 const FLAG_INDEXED: u32 = 1 << 29;
+const FLAG_MASK: u32 = FLAG_EXPIRING | FLAG_ENCODED | FLAG_INDEXED;
 
 pub struct DayFile {
     file: BufWriter<File>,
@@ -43,7 +44,7 @@ impl DayFile {
     }
 
     pub fn add_entry(&mut self, catindex: u16, subkeyindex: u16, value: &[u8],
-                     timestamp: f64, expiring: bool, dicts: &mut Dicts) -> io::Result<()> {
+                     timestamp: f64, expiring: bool, dicts: &Dicts) -> io::Result<()> {
         let mut msg = [0; 16];
         let length = value.len();
 
@@ -62,10 +63,192 @@ impl DayFile {
         LE::write_u16(&mut msg[4..], catindex);
         LE::write_u16(&mut msg[6..], subkeyindex);
         LE::write_f64(&mut msg[8..], timestamp);
-        self.file.write(&msg)?;
-        self.file.write(wvalue)?;
+        self.file.write_all(&msg)?;
+        self.file.write_all(wvalue)?;
         Ok(())
     }
+
+    /// Open an existing day file for reading, yielding its entries in
+    /// on-disk (append) order.
+    pub fn open(path: &Path) -> io::Result<DayFileReader> {
+        Ok(DayFileReader { file: BufReader::new(File::open(path)?), buf: vec![] })
+    }
+
+    /// Open a day file for appending new entries, creating it if it
+    /// doesn't exist yet. A half-written trailing record (e.g. left behind
+    /// by a process that was killed mid-write) is discarded first via
+    /// [`Self::validate_tail`], so appending can't corrupt the stream.
+    pub fn append(path: &Path) -> io::Result<Self> {
+        if !path.try_exists()? {
+            return Self::create(path);
+        }
+
+        let good_len = Self::validate_tail(path)?;
+        let file = fs_err::OpenOptions::new().write(true).open(path)?;
+        file.set_len(good_len)?;
+        let mut file = file;
+        file.seek(SeekFrom::Start(good_len))?;
+        Ok(Self { file: BufWriter::new(file), buf: vec![] })
+    }
+
+    /// Scan `path` from the start and return the byte length of its
+    /// longest prefix that consists only of complete records. Since the
+    /// format is a flat concatenation of self-delimiting records with no
+    /// global header, this is just reading records until one is missing
+    /// bytes at the end.
+    pub fn validate_tail(path: &Path) -> io::Result<u64> {
+        let mut reader = Self::open(path)?;
+        let mut good_len = reader.file.stream_position()?;
+        while let Some(result) = reader.next() {
+            match result {
+                Ok(_) => good_len = reader.file.stream_position()?,
+                Err(_) => break,
+            }
+        }
+        Ok(good_len)
+    }
+}
+
+/// A value as stored in a day file: either the bytes themselves, or an
+/// index into the value dictionary that must be resolved with [`Dicts`]
+/// to get the actual bytes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RawValue {
+    Inline(Vec<u8>),
+    Indexed(u32),
+}
+
+/// One entry read back from a day file, with the value dictionary lookup
+/// (if any) not yet resolved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawEntry {
+    pub catindex: u16,
+    pub subkeyindex: u16,
+    pub timestamp: f64,
+    pub value: RawValue,
+    pub expiring: bool,
+}
+
+/// One entry read back from a day file, with the value fully decoded to
+/// its original byte string.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub catindex: u16,
+    pub subkeyindex: u16,
+    pub timestamp: f64,
+    pub value: Vec<u8>,
+    pub expiring: bool,
+}
+
+impl RawEntry {
+    /// Resolve an indexed value against the value dictionary, producing the
+    /// original byte string either way. Fails if the value index is out of
+    /// bounds (e.g. a corrupt day file, or dictionaries from a different
+    /// database) rather than panicking.
+    pub fn resolve(&self, dicts: &Dicts) -> io::Result<Entry> {
+        let value = match &self.value {
+            RawValue::Inline(v) => v.clone(),
+            RawValue::Indexed(idx) => dicts.value(*idx)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData,
+                                               format!("value index {idx} out of bounds")))?
+                .to_vec(),
+        };
+        Ok(Entry {
+            catindex: self.catindex,
+            subkeyindex: self.subkeyindex,
+            timestamp: self.timestamp,
+            value,
+            expiring: self.expiring,
+        })
+    }
+}
+
+pub struct DayFileReader {
+    file: BufReader<File>,
+    buf: Vec<u8>,
+}
+
+impl DayFileReader {
+    /// Read the next raw entry, or `Ok(None)` at a clean end of file.
+    fn read_entry(&mut self) -> io::Result<Option<RawEntry>> {
+        let mut header = [0; 16];
+        if !self.fill_exact(&mut header)? {
+            return Ok(None);
+        }
+
+        let firstfield = LE::read_u32(&header[0..]);
+        let catindex = LE::read_u16(&header[4..]);
+        let subkeyindex = LE::read_u16(&header[6..]);
+        let timestamp = LE::read_f64(&header[8..]);
+        let expiring = firstfield & FLAG_EXPIRING != 0;
+        let data = firstfield & !FLAG_MASK;
+
+        let value = if firstfield & FLAG_INDEXED != 0 {
+            RawValue::Indexed(data)
+        } else if firstfield & FLAG_ENCODED != 0 {
+            let len = data as usize;
+            let mut payload = vec![0; len.div_ceil(2)];
+            self.file.read_exact(&mut payload)?;
+            RawValue::Inline(dec(&payload, len, &mut self.buf).to_vec())
+        } else {
+            let len = data as usize;
+            let mut payload = vec![0; len];
+            self.file.read_exact(&mut payload)?;
+            RawValue::Inline(payload)
+        };
+
+        Ok(Some(RawEntry { catindex, subkeyindex, timestamp, value, expiring }))
+    }
+
+    /// Fill `buf` completely, returning `Ok(false)` if the file ended
+    /// before a single byte was read (a clean EOF between records).
+    fn fill_exact(&mut self, buf: &mut [u8]) -> io::Result<bool> {
+        let mut read = 0;
+        while read < buf.len() {
+            let n = self.file.read(&mut buf[read..])?;
+            if n == 0 {
+                if read == 0 {
+                    return Ok(false);
+                }
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                           "day file ends with a truncated record"));
+            }
+            read += n;
+        }
+        Ok(true)
+    }
+
+    /// Wrap this reader so that it yields fully decoded [`Entry`] values,
+    /// resolving indexed values against `dicts` as it goes.
+    pub fn resolved(self, dicts: &Dicts) -> ResolvedEntries<'_> {
+        ResolvedEntries { inner: self, dicts }
+    }
+}
+
+impl Iterator for DayFileReader {
+    type Item = io::Result<RawEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_entry().transpose()
+    }
+}
+
+/// Iterator adapter that resolves [`RawEntry`] values from a [`DayFileReader`]
+/// into [`Entry`] values using a value dictionary.
+pub struct ResolvedEntries<'d> {
+    inner: DayFileReader,
+    dicts: &'d Dicts,
+}
+
+impl Iterator for ResolvedEntries<'_> {
+    type Item = io::Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.inner.next()? {
+            Ok(raw) => Some(raw.resolve(self.dicts)),
+            Err(e) => Some(Err(e)),
+        }
+    }
 }
 
 fn enc_map(b: u8) -> Option<u8> {
@@ -115,3 +298,101 @@ fn dec<'a>(value: &[u8], len: usize, buf: &'a mut Vec<u8>) -> &'a [u8] {
     buf.truncate(len);
     buf
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        static COUNTER: AtomicU32 = AtomicU32::new(0);
+        let n = COUNTER.fetch_add(1, Ordering::SeqCst);
+        std::env::temp_dir().join(format!("cachedb-test-{}-{}-{}", std::process::id(), n, name))
+    }
+
+    #[test]
+    fn enc_dec_round_trip() {
+        let mut buf = Vec::new();
+        // Even length: packs two digits per byte with no truncation needed.
+        let encoded = enc(b"12.345", &mut buf).unwrap().to_vec();
+        assert_eq!(dec(&encoded, 6, &mut buf), b"12.345");
+
+        // Odd length is the load-bearing case: the last byte only carries
+        // one real nibble, and dec() must truncate the padding nibble's
+        // decoded output back off rather than emitting it.
+        let encoded = enc(b"-12.3", &mut buf).unwrap().to_vec();
+        assert_eq!(encoded.len(), 3);
+        assert_eq!(dec(&encoded, 5, &mut buf), b"-12.3");
+    }
+
+    #[test]
+    fn enc_rejects_non_numeric() {
+        let mut buf = Vec::new();
+        assert!(enc(b"abc", &mut buf).is_none());
+    }
+
+    #[test]
+    fn entry_round_trip_inline_and_indexed() {
+        let path = temp_path("entries");
+        let dicts = Dicts::default();
+
+        let mut dayfile = DayFile::create(&path).unwrap();
+        dayfile.add_entry(1, 2, b"12.5", 100.0, false, &dicts).unwrap();
+        dayfile.add_entry(1, 3, b"hello", 200.0, true, &dicts).unwrap();
+        dayfile.add_entry(1, 4, b"-", 300.0, false, &dicts).unwrap();
+        drop(dayfile);
+
+        let entries: Vec<Entry> = DayFile::open(&path).unwrap()
+            .resolved(&dicts)
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+
+        assert_eq!(entries, vec![
+            Entry { catindex: 1, subkeyindex: 2, timestamp: 100.0, value: b"12.5".to_vec(), expiring: false },
+            Entry { catindex: 1, subkeyindex: 3, timestamp: 200.0, value: b"hello".to_vec(), expiring: true },
+            Entry { catindex: 1, subkeyindex: 4, timestamp: 300.0, value: b"-".to_vec(), expiring: false },
+        ]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn validate_tail_recovers_from_truncated_trailing_record() {
+        let path = temp_path("tail");
+        let dicts = Dicts::default();
+
+        let mut dayfile = DayFile::create(&path).unwrap();
+        dayfile.add_entry(1, 2, b"12.5", 100.0, false, &dicts).unwrap();
+        dayfile.file.flush().unwrap();
+        let good_len = std::fs::metadata(&path).unwrap().len();
+        dayfile.add_entry(1, 3, b"hello", 200.0, true, &dicts).unwrap();
+        drop(dayfile);
+
+        // Simulate a process killed mid-write: chop off the last few bytes
+        // of the second record so it's no longer complete.
+        let full_len = std::fs::metadata(&path).unwrap().len();
+        let file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(full_len - 3).unwrap();
+        drop(file);
+
+        assert_eq!(DayFile::validate_tail(&path).unwrap(), good_len);
+
+        // Appending after that should pick up right after the good record,
+        // discarding the truncated one rather than leaving it in place.
+        let mut dayfile = DayFile::append(&path).unwrap();
+        dayfile.add_entry(1, 4, b"99", 300.0, false, &dicts).unwrap();
+        drop(dayfile);
+
+        let entries: Vec<Entry> = DayFile::open(&path).unwrap()
+            .resolved(&dicts)
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+
+        assert_eq!(entries, vec![
+            Entry { catindex: 1, subkeyindex: 2, timestamp: 100.0, value: b"12.5".to_vec(), expiring: false },
+            Entry { catindex: 1, subkeyindex: 4, timestamp: 300.0, value: b"99".to_vec(), expiring: false },
+        ]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -22,73 +22,142 @@
 //
 //! Loading and storing of entries.
 
-use std::{convert::TryInto, path::Path, rc::Rc};
+use std::{path::Path, sync::Arc};
 use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::sync::{Mutex, RwLock};
+use std::sync::atomic::{AtomicU32, Ordering};
 use fs_err::File;
 
-#[derive(Default)]
+/// Number of lookup shards an interned [`Dict`] is split into, so that
+/// concurrent workers mostly take distinct locks instead of contending on
+/// one big map.
+const NUM_SHARDS: usize = 16;
+
+fn shard_for(val: &[u8]) -> usize {
+    let mut hasher = DefaultHasher::new();
+    val.hash(&mut hasher);
+    (hasher.finish() as usize) % NUM_SHARDS
+}
+
+/// A thread-safe string interner: looks up or assigns a stable `u32` index
+/// for a byte string, and can later resolve an index back to its bytes.
+///
+/// Lookups take only the shard lock for their string's hash. A new index
+/// is drawn from one atomic counter shared by all shards, so the index
+/// space stays a single global sequence regardless of which shard handed
+/// it out; the (much less contended) `by_index` lock is then taken to
+/// record the string for reverse lookups.
 struct Dict {
-    strs: Vec<Rc<[u8]>>,
-    indices: HashMap<Rc<[u8]>, u32>,
+    shards: Vec<RwLock<HashMap<Arc<[u8]>, u32>>>,
+    by_index: Mutex<Vec<Arc<[u8]>>>,
+    next_index: AtomicU32,
     max_index: u32,
 }
 
 impl Dict {
-    pub fn load(path: &Path, name: &str) -> io::Result<Self> {
+    fn new(max_index: u32) -> Self {
+        Self {
+            shards: (0..NUM_SHARDS).map(|_| RwLock::new(HashMap::new())).collect(),
+            by_index: Mutex::new(Vec::new()),
+            next_index: AtomicU32::new(0),
+            max_index,
+        }
+    }
+
+    pub fn load(path: &Path, name: &str, max_index: u32) -> io::Result<Self> {
         let file = BufReader::new(File::open(path.join(name))?);
-        let mut strs = Vec::new();
-        let mut indices = HashMap::new();
+        let dict = Self::new(max_index);
 
         for line in file.split(b'\n') {
             let line = line?;
-            let rc: Rc<[u8]> = line.into();
-            indices.insert(rc.clone(), strs.len() as u32);
-            strs.push(rc);
+            dict.intern(&line);
         }
 
-        Ok(Self { strs, indices, max_index: 0 })
+        Ok(dict)
     }
 
     pub fn save(&self, path: &Path, name: &str) -> io::Result<()> {
         let mut writer = BufWriter::new(File::create(path.join(name))?);
-        for s in &self.strs {
-            writer.write(s)?;
-            writer.write(b"\n")?;
+        for s in self.by_index.lock().unwrap().iter() {
+            writer.write_all(s)?;
+            writer.write_all(b"\n")?;
         }
         Ok(())
     }
 
-    pub fn index(&mut self, val: &[u8]) -> Option<u32> {
-        if let Some(n) = self.indices.get(val) {
-            return Some(*n);
+    /// Look up `val`'s index, interning it (assigning the next free index)
+    /// if this is the first time it's seen.
+    pub fn index(&self, val: &[u8]) -> Option<u32> {
+        if let Some(n) = self.lookup(val) {
+            return Some(n);
+        }
+        self.intern(val)
+    }
+
+    /// Look up `val`'s index without interning it.
+    pub fn find(&self, val: &[u8]) -> Option<u32> {
+        self.lookup(val)
+    }
+
+    fn lookup(&self, val: &[u8]) -> Option<u32> {
+        let shard = self.shards[shard_for(val)].read().unwrap();
+        shard.get(val).copied()
+    }
+
+    fn intern(&self, val: &[u8]) -> Option<u32> {
+        let mut shard = self.shards[shard_for(val)].write().unwrap();
+        // Another thread may have interned the same string while we waited
+        // for the write lock.
+        if let Some(&n) = shard.get(val) {
+            return Some(n);
         }
-        let new_index = self.strs.len().try_into().ok()?;
+
+        let new_index = self.next_index.fetch_add(1, Ordering::SeqCst);
         if new_index >= self.max_index {
+            self.next_index.fetch_sub(1, Ordering::SeqCst);
             return None;
         }
-        let rc: Rc<[u8]> = val.into();
-        self.indices.insert(rc.clone(), new_index);
-        self.strs.push(rc);
+
+        let rc: Arc<[u8]> = val.into();
+        shard.insert(rc.clone(), new_index);
+        drop(shard);
+
+        let mut by_index = self.by_index.lock().unwrap();
+        let idx = new_index as usize;
+        if by_index.len() <= idx {
+            by_index.resize(idx + 1, Arc::from(&b""[..]));
+        }
+        by_index[idx] = rc;
         Some(new_index)
     }
 
-    pub fn value(&self, index: u32) -> &[u8] {
-        &self.strs[index as usize]
+    /// Resolve an index back to its bytes, or `None` if it's out of bounds
+    /// (e.g. because the database is corrupt or was read with the wrong
+    /// dictionaries).
+    pub fn value(&self, index: u32) -> Option<Arc<[u8]>> {
+        self.by_index.lock().unwrap().get(index as usize).cloned()
     }
 }
 
+/// Thread-safe interner pair for keys (category/subkey names) and values,
+/// shared across the parallel day file conversion workers.
 pub struct Dicts {
     keys: Dict,
     vals: Dict,
 }
 
+/// Exclusive upper bound for value indices. The on-disk `FLAG_INDEXED`
+/// data field shares its 32-bit word with three flag bits (bits 29-31),
+/// leaving only 29 usable bits for the index.
+const MAX_VALUE_INDEX: u32 = 1 << 29;
+
 impl Default for Dicts {
     fn default() -> Self {
-        let mut keys = Dict::default();
-        let mut vals = Dict::default();
-        keys.max_index = u16::MAX as u32;
-        vals.max_index = (1 << 30) - 1;
+        let keys = Dict::new(u16::MAX as u32);
+        let vals = Dict::new(MAX_VALUE_INDEX);
         vals.index(b"-");
         Self { keys, vals }
     }
@@ -96,10 +165,8 @@ impl Default for Dicts {
 
 impl Dicts {
     pub fn load(path: &Path) -> io::Result<Self> {
-        let mut keys = Dict::load(path, "keys")?;
-        let mut vals = Dict::load(path, "values")?;
-        keys.max_index = u16::MAX as u32;
-        vals.max_index = (1 << 30) - 1;
+        let keys = Dict::load(path, "keys", u16::MAX as u32)?;
+        let vals = Dict::load(path, "values", MAX_VALUE_INDEX)?;
         Ok(Self { keys, vals })
     }
 
@@ -108,19 +175,98 @@ impl Dicts {
         self.vals.save(path, "values")
     }
 
-    pub fn key_index(&mut self, key: &[u8]) -> u16 {
+    pub fn key_index(&self, key: &[u8]) -> u16 {
         self.keys.index(key).expect("key overflow") as u16
     }
 
-    pub fn value_index(&mut self, val: &[u8]) -> u32 {
+    pub fn value_index(&self, val: &[u8]) -> u32 {
         self.vals.index(val).expect("value overflow")
     }
 
-    pub fn key(&self, index: u16) -> &[u8] {
+    /// Resolve a key index back to its bytes, or `None` if it's out of bounds.
+    pub fn key(&self, index: u16) -> Option<Arc<[u8]>> {
         self.keys.value(index as u32)
     }
 
-    pub fn value(&self, index: u32) -> &[u8] {
+    /// Resolve a value index back to its bytes, or `None` if it's out of bounds.
+    pub fn value(&self, index: u32) -> Option<Arc<[u8]>> {
         self.vals.value(index)
     }
+
+    /// Look up a key's index without interning it, for read-only queries.
+    pub fn find_key(&self, key: &[u8]) -> Option<u16> {
+        self.keys.find(key).map(|n| n as u16)
+    }
+
+    /// Number of distinct keys interned so far, i.e. the exclusive upper
+    /// bound for valid key indices.
+    pub fn key_count(&self) -> u32 {
+        self.keys.by_index.lock().unwrap().len() as u32
+    }
+
+    /// Number of distinct values interned so far, i.e. the exclusive upper
+    /// bound for valid value indices.
+    pub fn value_count(&self) -> u32 {
+        self.vals.by_index.lock().unwrap().len() as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn intern_round_trip() {
+        let dict = Dict::new(1000);
+        let a = dict.index(b"alpha").unwrap();
+        let b = dict.index(b"beta").unwrap();
+        assert_ne!(a, b);
+        // Re-interning returns the same index instead of a fresh one.
+        assert_eq!(dict.index(b"alpha").unwrap(), a);
+        assert_eq!(dict.value(a).unwrap().as_ref(), b"alpha");
+        assert_eq!(dict.value(b).unwrap().as_ref(), b"beta");
+    }
+
+    #[test]
+    fn value_out_of_bounds_is_none() {
+        let dict = Dict::new(1000);
+        assert!(dict.value(0).is_none());
+        dict.index(b"only one entry");
+        assert!(dict.value(1).is_none());
+    }
+
+    #[test]
+    fn max_index_is_enforced() {
+        let dict = Dict::new(1);
+        assert!(dict.index(b"first").is_some());
+        assert!(dict.index(b"second").is_none());
+    }
+
+    #[test]
+    fn concurrent_interning_yields_distinct_indices_for_every_string() {
+        let dict = Arc::new(Dict::new(10_000));
+        let handles: Vec<_> = (0..8).map(|t| {
+            let dict = dict.clone();
+            thread::spawn(move || -> Vec<(Vec<u8>, u32)> {
+                (0..50).map(|i| {
+                    let s = format!("thread{t}-val{i}").into_bytes();
+                    let idx = dict.index(&s).unwrap();
+                    (s, idx)
+                }).collect()
+            })
+        }).collect();
+
+        let mut seen = HashMap::new();
+        for handle in handles {
+            for (s, idx) in handle.join().unwrap() {
+                assert_eq!(dict.value(idx).unwrap().as_ref(), &s[..]);
+                if let Some(other) = seen.get(&idx) {
+                    assert_eq!(*other, s, "index {idx} assigned to two different strings");
+                } else {
+                    seen.insert(idx, s);
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,147 @@
+// -----------------------------------------------------------------------------
+// Compact cache database backend for NICOS.
+//
+// This program is free software; you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation; either version 2 of the License, or (at your option) any later
+// version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program; if not, write to the Free Software Foundation, Inc.,
+// 59 Temple Place, Suite 330, Boston, MA  02111-1307  USA
+//
+// Module authors:
+//   Georg Brandl <g.brandl@fz-juelich.de>
+//
+// -----------------------------------------------------------------------------
+//
+//! Time-range history queries across the day files of a converted database.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::dayfile::DayFile;
+use crate::dicts::Dicts;
+
+/// One decoded value change for a single key, as returned by [`HistoryStore::query`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub timestamp: f64,
+    pub value: Vec<u8>,
+    pub expiring: bool,
+}
+
+/// Read-only view over a converted database directory that answers
+/// time-range history queries for a single key without the caller having
+/// to iterate day files by hand.
+pub struct HistoryStore {
+    outdir: PathBuf,
+    dicts: Dicts,
+}
+
+impl HistoryStore {
+    pub fn open(outdir: &Path) -> io::Result<Self> {
+        Ok(Self { outdir: outdir.to_path_buf(), dicts: Dicts::load(outdir)? })
+    }
+
+    /// Look up the history of `cat`/`subkey` within `[from, to]`, in
+    /// chronological order.
+    pub fn query(&self, cat: &[u8], subkey: &[u8], from: f64, to: f64) -> io::Result<Vec<HistoryEntry>> {
+        let (catindex, subkeyindex) = match (self.dicts.find_key(cat), self.dicts.find_key(subkey)) {
+            (Some(c), Some(s)) => (c, s),
+            // Key was never seen, so it has no history.
+            _ => return Ok(Vec::new()),
+        };
+
+        let mut per_day = Vec::new();
+        for path in self.day_files_in_range(from, to)? {
+            let mut day_entries = Vec::new();
+            for entry in DayFile::open(&path)?.resolved(&self.dicts) {
+                let entry = entry?;
+                if entry.catindex == catindex && entry.subkeyindex == subkeyindex
+                    && entry.timestamp >= from && entry.timestamp <= to
+                {
+                    day_entries.push(HistoryEntry {
+                        timestamp: entry.timestamp,
+                        value: entry.value,
+                        expiring: entry.expiring,
+                    });
+                }
+            }
+            // Entries within a day are append-ordered, not time-sorted.
+            day_entries.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+            per_day.push(day_entries);
+        }
+
+        Ok(per_day.into_iter().fold(Vec::new(), merge_sorted))
+    }
+
+    /// Day files (named `YYYY-MM-DD`) whose calendar day overlaps `[from, to]`,
+    /// in chronological order.
+    ///
+    /// A day file is named after the *source* directory it was converted
+    /// from, not after the timestamps of the entries it holds (which are
+    /// append-ordered, not time-sorted), so an entry can land a day off
+    /// from its own timestamp (e.g. a local-time/UTC split at midnight).
+    /// The window is padded by a full day on each side so such entries
+    /// aren't filtered out before the exact per-entry check in `query`.
+    fn day_files_in_range(&self, from: f64, to: f64) -> io::Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        for entry in fs_err::read_dir(&self.outdir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let Some(name) = name.to_str() else { continue };
+            let Some((year, month, day)) = parse_day_filename(name) else { continue };
+
+            let day_start = days_from_civil(year, month, day) as f64 * 86400.0;
+            if day_start - 86400.0 < to && day_start + 2.0 * 86400.0 > from {
+                files.push((name.to_string(), entry.path()));
+            }
+        }
+        files.sort();
+        Ok(files.into_iter().map(|(_, path)| path).collect())
+    }
+}
+
+fn merge_sorted(a: Vec<HistoryEntry>, b: Vec<HistoryEntry>) -> Vec<HistoryEntry> {
+    let mut result = Vec::with_capacity(a.len() + b.len());
+    let mut a = a.into_iter().peekable();
+    let mut b = b.into_iter().peekable();
+    loop {
+        match (a.peek(), b.peek()) {
+            (Some(x), Some(y)) if x.timestamp <= y.timestamp => result.push(a.next().unwrap()),
+            (Some(_), Some(_)) => result.push(b.next().unwrap()),
+            (Some(_), None) => result.push(a.next().unwrap()),
+            (None, Some(_)) => result.push(b.next().unwrap()),
+            (None, None) => break,
+        }
+    }
+    result
+}
+
+/// Parse a day file's name (`YYYY-MM-DD`) into its `(year, month, day)`
+/// components, or `None` if `name` isn't a day file.
+pub fn parse_day_filename(name: &str) -> Option<(i64, i64, i64)> {
+    let mut parts = name.splitn(3, '-');
+    let year = parts.next()?.parse().ok()?;
+    let month = parts.next()?.parse().ok()?;
+    let day = parts.next()?.parse().ok()?;
+    Some((year, month, day))
+}
+
+/// Days since 1970-01-01 for a given civil date (Howard Hinnant's
+/// `days_from_civil` algorithm), used to turn day file names into epoch
+/// timestamps for range overlap checks.
+fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let mp = (m + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}